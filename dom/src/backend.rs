@@ -0,0 +1,276 @@
+//! Abstracts the operations [`MemoElement`](crate::MemoElement) needs to perform against a tree of
+//! nodes, so that the same incremental declarations can target a live browser DOM or an in-memory
+//! tree suitable for server-side rendering and tests that don't require a headless browser.
+
+use std::{cell::RefCell, fmt::Write, rc::Rc};
+
+/// The operations a tree of nodes must support in order to back [`MemoElement`](crate::MemoElement).
+///
+/// [`WebSysBackend`] is the default, implementing this in terms of `web_sys`'s live browser DOM.
+/// [`StringBackend`] builds an in-memory tree instead, for use with [`render_to_string`].
+pub trait DomBackend {
+    /// A node in the tree. Text nodes and elements are both nodes.
+    type Node: Clone + PartialEq;
+    /// An element, which is always also a [`Node`](Self::Node).
+    type Element: Clone + Into<Self::Node>;
+
+    /// Create a detached element of the given type, e.g. `"div"`.
+    fn create_element(&self, ty: &str) -> Self::Element;
+    /// Create a detached text node with the given contents.
+    fn create_text(&self, text: &str) -> Self::Node;
+    /// Set an attribute on `elem`.
+    fn set_attribute(&self, elem: &Self::Element, name: &str, value: &str);
+    /// Remove an attribute from `elem`.
+    fn remove_attribute(&self, elem: &Self::Element, name: &str);
+    /// Append `child` as the last child of `parent`.
+    fn append_child(&self, parent: &Self::Element, child: &Self::Node);
+    /// Replace `existing` with `new_child` in `parent`'s children.
+    fn replace_child(&self, parent: &Self::Element, new_child: &Self::Node, existing: &Self::Node);
+    /// Remove `child` from `parent`'s children.
+    fn remove_child(&self, parent: &Self::Element, child: &Self::Node);
+    /// Insert `child` as a child of `parent`, immediately before `reference`, or as the last
+    /// child if `reference` is `None`. Used by keyed reconciliation to move an existing child
+    /// into place without tearing it down.
+    fn insert_before(&self, parent: &Self::Element, child: &Self::Node, reference: Option<&Self::Node>);
+    /// The first child of `parent`, if any.
+    fn first_child(&self, parent: &Self::Element) -> Option<Self::Node>;
+    /// The sibling immediately following `node`, if any.
+    fn next_sibling(&self, node: &Self::Node) -> Option<Self::Node>;
+}
+
+/// The default [`DomBackend`], performing mutations against the live `web_sys` DOM.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebSysBackend;
+
+impl DomBackend for WebSysBackend {
+    type Node = crate::sys::Node;
+    type Element = crate::sys::Element;
+
+    fn create_element(&self, ty: &str) -> Self::Element {
+        crate::document().create_element(ty).unwrap()
+    }
+
+    fn create_text(&self, text: &str) -> Self::Node {
+        crate::document().create_text_node(text).into()
+    }
+
+    fn set_attribute(&self, elem: &Self::Element, name: &str, value: &str) {
+        elem.set_attribute(name, value).unwrap();
+    }
+
+    fn remove_attribute(&self, elem: &Self::Element, name: &str) {
+        elem.remove_attribute(name).unwrap();
+    }
+
+    fn append_child(&self, parent: &Self::Element, child: &Self::Node) {
+        crate::sys::Node::from(parent.clone())
+            .append_child(child)
+            .unwrap();
+    }
+
+    fn replace_child(&self, parent: &Self::Element, new_child: &Self::Node, existing: &Self::Node) {
+        crate::sys::Node::from(parent.clone())
+            .replace_child(new_child, existing)
+            .unwrap();
+    }
+
+    fn remove_child(&self, parent: &Self::Element, child: &Self::Node) {
+        crate::sys::Node::from(parent.clone())
+            .remove_child(child)
+            .unwrap();
+    }
+
+    fn insert_before(&self, parent: &Self::Element, child: &Self::Node, reference: Option<&Self::Node>) {
+        crate::sys::Node::from(parent.clone())
+            .insert_before(child, reference)
+            .unwrap();
+    }
+
+    fn first_child(&self, parent: &Self::Element) -> Option<Self::Node> {
+        crate::sys::Node::from(parent.clone()).first_child()
+    }
+
+    fn next_sibling(&self, node: &Self::Node) -> Option<Self::Node> {
+        node.next_sibling()
+    }
+}
+
+/// An in-memory node, used by [`StringBackend`] to build a tree that can be serialized to HTML
+/// without a live browser `window`.
+#[derive(Clone)]
+pub enum StringNode {
+    /// A text node, holding its literal contents.
+    Text(Rc<RefCell<TextData>>),
+    /// An element, holding its tag name, attributes, and children.
+    Element(Rc<RefCell<StringElement>>),
+}
+
+/// The contents of a [`StringNode::Text`].
+pub struct TextData {
+    /// The text node's contents.
+    pub value: String,
+    parent: Option<Rc<RefCell<StringElement>>>,
+}
+
+/// The contents of an in-memory [`StringNode::Element`].
+pub struct StringElement {
+    /// The element's tag name, e.g. `"div"`.
+    pub ty: String,
+    /// Attributes in insertion order, as `(name, value)` pairs.
+    pub attributes: Vec<(String, String)>,
+    /// This element's children, in document order.
+    pub children: Vec<StringNode>,
+    parent: Option<Rc<RefCell<StringElement>>>,
+}
+
+impl PartialEq for StringNode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StringNode::Text(a), StringNode::Text(b)) => Rc::ptr_eq(a, b),
+            (StringNode::Element(a), StringNode::Element(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<Rc<RefCell<StringElement>>> for StringNode {
+    fn from(elem: Rc<RefCell<StringElement>>) -> Self {
+        StringNode::Element(elem)
+    }
+}
+
+impl StringNode {
+    fn set_parent(&self, parent: &Rc<RefCell<StringElement>>) {
+        match self {
+            StringNode::Text(t) => t.borrow_mut().parent = Some(parent.clone()),
+            StringNode::Element(e) => e.borrow_mut().parent = Some(parent.clone()),
+        }
+    }
+
+    fn parent(&self) -> Option<Rc<RefCell<StringElement>>> {
+        match self {
+            StringNode::Text(t) => t.borrow().parent.clone(),
+            StringNode::Element(e) => e.borrow().parent.clone(),
+        }
+    }
+}
+
+/// A [`DomBackend`] which builds an in-memory tree instead of touching a live DOM, allowing
+/// [`render_to_string`] and other offline tests of component output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StringBackend;
+
+impl DomBackend for StringBackend {
+    type Node = StringNode;
+    type Element = Rc<RefCell<StringElement>>;
+
+    fn create_element(&self, ty: &str) -> Self::Element {
+        Rc::new(RefCell::new(StringElement {
+            ty: ty.to_string(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            parent: None,
+        }))
+    }
+
+    fn create_text(&self, text: &str) -> Self::Node {
+        StringNode::Text(Rc::new(RefCell::new(TextData {
+            value: text.to_string(),
+            parent: None,
+        })))
+    }
+
+    fn set_attribute(&self, elem: &Self::Element, name: &str, value: &str) {
+        let mut elem = elem.borrow_mut();
+        match elem.attributes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => elem.attributes.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    fn remove_attribute(&self, elem: &Self::Element, name: &str) {
+        elem.borrow_mut().attributes.retain(|(n, _)| n != name);
+    }
+
+    fn append_child(&self, parent: &Self::Element, child: &Self::Node) {
+        child.set_parent(parent);
+        parent.borrow_mut().children.push(child.clone());
+    }
+
+    fn replace_child(&self, parent: &Self::Element, new_child: &Self::Node, existing: &Self::Node) {
+        new_child.set_parent(parent);
+        let mut parent = parent.borrow_mut();
+        if let Some(slot) = parent.children.iter_mut().find(|c| *c == existing) {
+            *slot = new_child.clone();
+        }
+    }
+
+    fn remove_child(&self, parent: &Self::Element, child: &Self::Node) {
+        parent.borrow_mut().children.retain(|c| c != child);
+    }
+
+    fn insert_before(&self, parent: &Self::Element, child: &Self::Node, reference: Option<&Self::Node>) {
+        child.set_parent(parent);
+        let mut parent = parent.borrow_mut();
+        parent.children.retain(|c| c != child);
+        let index = match reference {
+            Some(reference) => parent.children.iter().position(|c| c == reference).unwrap_or(parent.children.len()),
+            None => parent.children.len(),
+        };
+        parent.children.insert(index, child.clone());
+    }
+
+    fn first_child(&self, parent: &Self::Element) -> Option<Self::Node> {
+        parent.borrow().children.first().cloned()
+    }
+
+    fn next_sibling(&self, node: &Self::Node) -> Option<Self::Node> {
+        let parent = node.parent()?;
+        let parent = parent.borrow();
+        let index = parent.children.iter().position(|c| c == node)?;
+        parent.children.get(index + 1).cloned()
+    }
+}
+
+fn write_escaped(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn write_node(out: &mut String, node: &StringNode) {
+    match node {
+        StringNode::Text(text) => write_escaped(out, &text.borrow().value),
+        StringNode::Element(elem) => {
+            let elem = elem.borrow();
+            write!(out, "<{}", elem.ty).unwrap();
+            for (name, value) in &elem.attributes {
+                write!(out, " {}=\"", name).unwrap();
+                write_escaped(out, value);
+                out.push('"');
+            }
+            out.push('>');
+            for child in &elem.children {
+                write_node(out, child);
+            }
+            write!(out, "</{}>", elem.ty).unwrap();
+        }
+    }
+}
+
+/// Serialize `root`'s children, an in-memory tree already built by a [`StringBackend`], to an
+/// HTML string. `root` itself is not included in the output. This does not run any declaration;
+/// [`crate::render_to_string`] drives one against a [`StringBackend`] and then calls this.
+pub fn render_to_string(root: Rc<RefCell<StringElement>>) -> String {
+    let mut out = String::new();
+    for child in &root.borrow().children {
+        write_node(&mut out, child);
+    }
+    out
+}
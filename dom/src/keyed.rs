@@ -0,0 +1,185 @@
+//! Keyed reconciliation for [`MemoElement::inner`](crate::MemoElement::inner), so that reordering
+//! a list of children moves their existing nodes (and memoized state) into place instead of
+//! tearing down and rebuilding everything from the point of the first change.
+
+use {
+    crate::backend::DomBackend,
+    std::{
+        collections::HashSet,
+        hash::{Hash, Hasher},
+    },
+};
+
+/// Identifies a child across `Revision`s so [`MemoElement::inner`](crate::MemoElement::inner) can
+/// preserve its DOM node (and the moxie state memoized under it) when the child moves relative to
+/// its siblings. Construct one with [`Key::new`], typically from a list item's own id.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Key(u64);
+
+impl Key {
+    /// Derive a key from any [`Hash`]able value.
+    pub fn new(value: impl Hash) -> Self {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        value.hash(&mut hasher);
+        Key(hasher.finish())
+    }
+}
+
+/// Declares `child` as identified by `key` for the purposes of its enclosing
+/// [`MemoElement::inner`](crate::MemoElement::inner) scope. Nesting a child's declaration in
+/// `keyed` does two things: it puts `key` in `child`'s topological slot, so memoization inside
+/// `child` follows the key rather than declaration order (surviving a reorder), and it tells
+/// `inner` to reconcile this child by identity instead of position.
+#[topo::aware]
+pub fn keyed<Ret>(key: Key, child: impl FnOnce() -> Ret) -> Ret {
+    topo::call!(slot: key, {
+        topo::call!({ child() }, env! { Key => key })
+    })
+}
+
+pub(crate) fn is_keyed() -> bool {
+    topo::Env::get::<Key>().is_some()
+}
+
+/// Reorders `parent`'s actual children to match `desired`, moving already-present nodes via
+/// [`DomBackend::insert_before`] rather than recreating them, and removing whatever's left over.
+///
+/// `desired` nodes are matched against the parent's current children by identity
+/// (`DomBackend::Node`'s `PartialEq`) -- which, for a child declared under [`keyed`], is stable
+/// across reorders because its memoization follows its key rather than its position. The subset of
+/// matched nodes already in increasing relative order (the longest increasing subsequence of their
+/// old positions) is left untouched; every other matched node is moved to sit just before its
+/// successor in `desired`, and unmatched nodes are freshly inserted at their slot.
+pub(crate) fn reconcile<B: DomBackend>(backend: &B, parent: &B::Element, desired: &[B::Node]) {
+    let mut current = Vec::new();
+    let mut next = backend.first_child(parent);
+    while let Some(node) = next {
+        next = backend.next_sibling(&node);
+        current.push(node);
+    }
+
+    let old_index: Vec<Option<usize>> =
+        desired.iter().map(|node| current.iter().position(|c| c == node)).collect();
+    let stable = longest_increasing_subsequence(&old_index);
+
+    // process back-to-front so each node's chosen `reference` sibling is already in its final
+    // position by the time we get to it
+    for i in (0..desired.len()).rev() {
+        if stable.contains(&i) {
+            continue;
+        }
+        let reference = desired.get(i + 1);
+        backend.insert_before(parent, &desired[i], reference);
+    }
+
+    for node in &current {
+        if !desired.contains(node) {
+            backend.remove_child(parent, node);
+        }
+    }
+}
+
+/// Returns the indices (into `old_index`) of the longest increasing subsequence of its `Some`
+/// entries, via patience sorting. `None` entries (nodes with no prior position, i.e. brand new)
+/// never participate.
+fn longest_increasing_subsequence(old_index: &[Option<usize>]) -> HashSet<usize> {
+    let present: Vec<usize> =
+        old_index.iter().enumerate().filter_map(|(i, m)| m.map(|_| i)).collect();
+
+    let mut pile_tops: Vec<usize> = Vec::new(); // indices into `present`
+    let mut predecessor: Vec<Option<usize>> = vec![None; present.len()];
+
+    for (pi, &i) in present.iter().enumerate() {
+        let value = old_index[i].unwrap();
+        let pos = pile_tops.partition_point(|&top| old_index[present[top]].unwrap() < value);
+        if pos > 0 {
+            predecessor[pi] = Some(pile_tops[pos - 1]);
+        }
+        if pos == pile_tops.len() {
+            pile_tops.push(pi);
+        } else {
+            pile_tops[pos] = pi;
+        }
+    }
+
+    let mut result = HashSet::new();
+    let mut cursor = pile_tops.last().copied();
+    while let Some(pi) = cursor {
+        result.insert(present[pi]);
+        cursor = predecessor[pi];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::backend::{DomBackend, StringBackend},
+    };
+
+    fn children_of(backend: &StringBackend, parent: &<StringBackend as DomBackend>::Element) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut next = backend.first_child(parent);
+        while let Some(node) = next {
+            next = backend.next_sibling(&node);
+            names.push(match &node {
+                crate::backend::StringNode::Element(e) => e.borrow().ty.clone(),
+                crate::backend::StringNode::Text(t) => t.borrow().value.clone(),
+            });
+        }
+        names
+    }
+
+    #[test]
+    fn reconcile_reorders_existing_nodes_without_recreating_them() {
+        let backend = StringBackend;
+        let parent = backend.create_element("ul");
+        let a = backend.create_element("a").into();
+        let b = backend.create_element("b").into();
+        let c = backend.create_element("c").into();
+        let d = backend.create_element("d").into();
+
+        reconcile(&backend, &parent, &[a.clone(), b.clone(), c.clone(), d.clone()]);
+        assert_eq!(children_of(&backend, &parent), vec!["a", "b", "c", "d"]);
+
+        // rotate the list: only `a` needs to move, `b`/`c`/`d` are already in increasing order
+        reconcile(&backend, &parent, &[b.clone(), c.clone(), d.clone(), a.clone()]);
+        assert_eq!(children_of(&backend, &parent), vec!["b", "c", "d", "a"]);
+
+        // the same nodes are still attached, just moved -- not recreated
+        assert_eq!(backend.first_child(&parent).unwrap(), b);
+    }
+
+    #[test]
+    fn reconcile_inserts_new_and_removes_missing_nodes() {
+        let backend = StringBackend;
+        let parent = backend.create_element("ul");
+        let a = backend.create_element("a").into();
+        let b = backend.create_element("b").into();
+        let c = backend.create_element("c").into();
+
+        reconcile(&backend, &parent, &[a.clone(), b.clone()]);
+        assert_eq!(children_of(&backend, &parent), vec!["a", "b"]);
+
+        // drop `a`, keep `b`, add `c`
+        reconcile(&backend, &parent, &[b, c]);
+        assert_eq!(children_of(&backend, &parent), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn lis_keeps_the_longest_run_already_in_order() {
+        // several runs of length 2 end in value 3; patience sorting keeps the one through the
+        // smallest pile top, so positions 2 and 3 (values 0, 3) are chosen
+        let old_index = vec![Some(2), Some(1), Some(0), Some(3)];
+        let stable = longest_increasing_subsequence(&old_index);
+        assert_eq!(stable, [2, 3].iter().copied().collect());
+    }
+
+    #[test]
+    fn lis_ignores_brand_new_entries() {
+        let old_index = vec![Some(0), None, Some(1)];
+        let stable = longest_increasing_subsequence(&old_index);
+        assert_eq!(stable, [0, 2].iter().copied().collect());
+    }
+}
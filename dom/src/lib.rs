@@ -8,17 +8,21 @@ pub use moxie::*;
 
 use {
     crate::{
+        backend::{DomBackend, WebSysBackend},
         embed::WebRuntime,
         event::{Event, EventHandle},
     },
     moxie,
-    std::cell::Cell,
+    std::cell::{Cell, RefCell},
     tracing::*,
 };
 
+pub mod backend;
 pub mod elements;
 pub mod embed;
 pub mod event;
+pub mod keyed;
+pub mod mutations;
 
 pub use web_sys as sys;
 
@@ -39,6 +43,20 @@ pub fn boot(new_parent: impl AsRef<sys::Element> + 'static, root: impl FnMut() +
         .run_on_state_changes();
 }
 
+/// Drive `root` once against an in-memory tree rather than a live `window`, then serialize the
+/// result to an HTML string. This is the entry point for server-side rendering and for tests of
+/// component output that don't have (or want) a headless browser available.
+///
+/// Unlike [`boot`], this runs the declaration a single time: there's no live DOM for state changes
+/// to re-render into, so callers that need to respond to further updates should re-invoke this for
+/// each new render they want to produce.
+pub fn render_to_string(mut root: impl FnMut()) -> String {
+    let backend = backend::StringBackend;
+    let document_elem = backend.create_element("html");
+    MemoElement::new(backend, document_elem.clone()).inner(|| root());
+    backend::render_to_string(document_elem)
+}
+
 /// Returns the current window. Panics if no window is available.
 pub fn window() -> sys::Window {
     sys::window().expect("must run from within a `window`")
@@ -54,12 +72,12 @@ pub fn document() -> sys::Document {
 /// Create and mount a [DOM text node](https://developer.mozilla.org/en-US/docs/Web/API/Text).
 /// This is normally called by the [`moxie::mox!`] macro.
 #[topo::aware]
-#[topo::from_env(parent: MemoElement)]
-pub fn text(s: impl ToString) {
+#[topo::from_env(parent: MemoElement<B>)]
+pub fn text<B: DomBackend + 'static>(s: impl ToString) {
     // TODO consider a ToOwned-based memoization API that's lower level?
     // memo_ref<Ref, Arg, Output>(reference: Ref, init: impl FnOnce(Arg) -> Output)
     // where Ref: ToOwned<Owned=Arg> + PartialEq, etcetcetc
-    let text_node = memo!(s.to_string(), |s| document().create_text_node(s));
+    let text_node = memo!(s.to_string(), |s| parent.backend.create_text(s));
     parent.ensure_child_attached(&text_node);
 }
 
@@ -73,46 +91,63 @@ pub fn text(s: impl ToString) {
 /// Mutation of the created element is performed during the `with_elem` closure via the provided
 /// [`moxie_dom::MemoElement`] wrapper.
 #[topo::aware]
-#[topo::from_env(parent: MemoElement)]
-pub fn element<ChildRet>(
+#[topo::from_env(parent: MemoElement<B>)]
+pub fn element<B: DomBackend + 'static, ChildRet>(
     ty: &'static str,
-    with_elem: impl FnOnce(&MemoElement) -> ChildRet,
+    with_elem: impl FnOnce(&MemoElement<B>) -> ChildRet,
 ) -> ChildRet {
-    let elem = memo!(ty, |ty| document().create_element(ty).unwrap());
-    parent.ensure_child_attached(&elem);
-    let elem = MemoElement::new(elem);
+    let elem = memo!(ty, |ty| parent.backend.create_element(ty));
+    parent.ensure_child_attached(&elem.clone().into());
+    let elem = MemoElement::new(parent.backend.clone(), elem);
     with_elem(&elem)
 }
 
+/// Declare an ordered run of sibling nodes that attach directly to the nearest enclosing
+/// [`MemoElement::inner`] scope, without an intervening wrapper element.
+///
+/// `text` and `element` each attach exactly one node, so without this a component that wants to
+/// produce several top-level siblings would have to wrap them in a container it doesn't actually
+/// want in the output. `fragment` doesn't introduce an element of its own: it just runs `children`
+/// with the enclosing [`MemoElement`] still in scope, so any nodes declared inside become siblings
+/// of whatever surrounds the `fragment` call. Because cleanup of trailing children in `inner` is
+/// already driven by "everything after the last node this revision attached" rather than a fixed
+/// count, a fragment that shrinks across `Revision`s is cleaned up the same way any other trailing
+/// child would be -- there's nothing fragment-specific to do there.
+#[topo::aware]
+pub fn fragment(children: impl FnOnce()) {
+    children();
+}
+
 /// A topologically-aware "incremental smart pointer" for an HTML element.
 ///
 /// Created during execution of the (element) macro and the element-specific wrappers. Offers a
 /// "stringly-typed" API for mutating the contained DOM nodes, adhering fairly closely to the
 /// upstream web specs.
-pub struct MemoElement {
-    curr: Cell<Option<sys::Node>>,
-    elem: sys::Element,
+///
+/// Generic over the [`DomBackend`] performing the actual mutations, so the same declarations can
+/// drive a live `web_sys` DOM ([`WebSysBackend`], the default) or an in-memory tree suitable for
+/// server-side rendering ([`backend::StringBackend`]).
+pub struct MemoElement<B: DomBackend = WebSysBackend> {
+    elem: B::Element,
+    pub(crate) backend: B,
+    desired_children: RefCell<Vec<B::Node>>,
+    any_keyed: Cell<bool>,
 }
 
-impl MemoElement {
-    fn new(elem: sys::Element) -> Self {
+impl<B: DomBackend + Clone> MemoElement<B> {
+    fn new(backend: B, elem: B::Element) -> Self {
         Self {
-            curr: Cell::new(None),
             elem,
+            backend,
+            desired_children: RefCell::new(Vec::new()),
+            any_keyed: Cell::new(false),
         }
     }
 
-    /// Retrieves access to the raw HTML element underlying the (MemoElement).
-    ///
-    /// Because this offers an escape hatch around the memoized mutations, it should be used with
-    /// caution. Also because of this, it has a silly name intended to loudly announce that
-    /// care must be taken.
-    ///
-    /// Code called by the root function of your application will be run quite frequently and
-    /// so the tools for memoization are important for keeping your application responsive. If you
-    /// have legitimate needs for this API, please consider filing an issue with your use case so
-    /// the maintainers of this crate can consider "official" ways to support it.
-    pub fn raw_element_that_has_sharp_edges_please_be_careful(&self) -> sys::Element {
+    /// The backend-specific handle for this element, e.g. a `sys::Element` or a
+    /// [`mutations::NodeId`](crate::mutations::NodeId). Used by backends that need to key
+    /// auxiliary per-element state off of the same identity `MemoElement` itself uses.
+    pub(crate) fn raw_node_id(&self) -> B::Element {
         self.elem.clone()
     }
 
@@ -129,9 +164,10 @@ impl MemoElement {
             memo_with!(
                 value.to_string(),
                 |v| {
-                    self.elem.set_attribute(name, v).unwrap();
+                    self.backend.set_attribute(&self.elem, name, v);
+                    let backend = self.backend.clone();
                     scopeguard::guard(self.elem.clone(), move |elem| {
-                        elem.remove_attribute(name).unwrap()
+                        backend.remove_attribute(&elem, name)
                     })
                 },
                 |_| {}
@@ -140,6 +176,109 @@ impl MemoElement {
         self
     }
 
+    fn ensure_child_attached(&self, new_child: &B::Node) {
+        self.desired_children.borrow_mut().push(new_child.clone());
+        if keyed::is_keyed() {
+            self.any_keyed.set(true);
+        }
+    }
+
+    /// Declare the inner contents of the element, usually declaring children within the inner
+    /// scope. Once `children` has run and the full list of desired children for this `Revision` is
+    /// known, they're attached in a single pass: by identity via [`keyed::reconcile`] if any child
+    /// was declared via [`keyed`](crate::keyed::keyed), or otherwise positionally, matching each
+    /// desired child against whatever currently sits in that position and replacing it if they
+    /// differ. Deferring attachment until the full list is known (rather than attaching each child
+    /// positionally as it's declared) means a keyed scope is reconciled in one pass against the DOM
+    /// as it stood at the start of this `Revision`, instead of against positions already disturbed
+    /// by attaching its own children. Finally, this clears any trailing child nodes left over from
+    /// the previous `Revision` to ensure the element's children are correct per the latest
+    /// declaration.
+    // FIXME this should be topo-aware
+    pub fn inner<Ret>(&self, children: impl FnOnce() -> Ret) -> Ret
+    where
+        B: 'static,
+    {
+        let elem = self.elem.clone();
+        let backend = self.backend.clone();
+        let desired_children;
+        let any_keyed;
+        let ret;
+        topo::call!(
+            {
+                ret = children();
+
+                // before this melement is dropped when the environment goes out of scope, we
+                // need to pull out what was recorded against it this revision
+                let inner = topo::Env::expect::<MemoElement<B>>();
+                desired_children = inner.desired_children.replace(Vec::new());
+                any_keyed = inner.any_keyed.get();
+            },
+            env! {
+                MemoElement<B> => MemoElement::new(self.backend.clone(), self.elem.clone()),
+            }
+        );
+
+        if any_keyed {
+            keyed::reconcile(&backend, &elem, &desired_children);
+        } else {
+            attach_positionally(&backend, &elem, &desired_children);
+        }
+
+        // if there weren't any children declared this revision, we need to make sure we clean up
+        // any from the last revision
+        let mut next_to_remove = match desired_children.last() {
+            Some(c) => backend.next_sibling(c),
+            None => backend.first_child(&elem),
+        };
+
+        while let Some(to_remove) = next_to_remove {
+            next_to_remove = backend.next_sibling(&to_remove);
+            backend.remove_child(&elem, &to_remove);
+        }
+
+        ret
+    }
+}
+
+/// Attaches `desired` to `elem` by position, matching each entry against whatever currently
+/// occupies that slot and replacing it if they differ, or appending if `desired` is longer than
+/// the current children. Used by [`MemoElement::inner`] when no child in the scope was declared
+/// via [`keyed`](crate::keyed::keyed), so there's no identity to reconcile by and positional replace
+/// is already minimal.
+fn attach_positionally<B: DomBackend>(backend: &B, elem: &B::Element, desired: &[B::Node]) {
+    let mut prev: Option<B::Node> = None;
+    for child in desired {
+        let existing = match &prev {
+            Some(p) => backend.next_sibling(p),
+            None => backend.first_child(elem),
+        };
+
+        match existing {
+            Some(existing) if existing == *child => {}
+            Some(existing) => backend.replace_child(elem, child, &existing),
+            None => backend.append_child(elem, child),
+        }
+
+        prev = Some(child.clone());
+    }
+}
+
+impl MemoElement<WebSysBackend> {
+    /// Retrieves access to the raw HTML element underlying the (MemoElement).
+    ///
+    /// Because this offers an escape hatch around the memoized mutations, it should be used with
+    /// caution. Also because of this, it has a silly name intended to loudly announce that
+    /// care must be taken.
+    ///
+    /// Code called by the root function of your application will be run quite frequently and
+    /// so the tools for memoization are important for keeping your application responsive. If you
+    /// have legitimate needs for this API, please consider filing an issue with your use case so
+    /// the maintainers of this crate can consider "official" ways to support it.
+    pub fn raw_element_that_has_sharp_edges_please_be_careful(&self) -> sys::Element {
+        self.elem.clone()
+    }
+
     // FIXME this should be topo-aware
     /// Declare an event handler on the element.
     ///
@@ -149,6 +288,10 @@ impl MemoElement {
     ///
     /// Currently this is performed on every Revision, as changes to event handlers don't typically
     /// affect the debugging experience and have not yet shown up in performance profiles.
+    ///
+    /// Event handlers are inherently tied to a live `web_sys::EventTarget`, so this is only
+    /// available on the `web_sys`-backed [`MemoElement`]; there is nothing for it to attach to
+    /// when rendering to a [`backend::StringBackend`].
     pub fn on<Ev>(&self, callback: impl FnMut(Ev) + 'static) -> &Self
     where
         Ev: 'static + Event,
@@ -166,58 +309,62 @@ impl MemoElement {
         self
     }
 
-    fn ensure_child_attached(&self, new_child: &sys::Node) {
-        let prev_sibling = self.curr.replace(Some(new_child.clone()));
-
-        let existing = if prev_sibling.is_none() {
-            self.elem.first_child()
-        } else {
-            prev_sibling.and_then(|p| p.next_sibling())
-        };
-
-        if let Some(existing) = existing {
-            if !existing.is_same_node(Some(new_child)) {
-                self.elem.replace_child(new_child, &existing).unwrap();
-            }
-        } else {
-            self.elem.append_child(new_child).unwrap();
-        }
+    /// Declare a DOM *property* (as opposed to an attribute) on the element, re-asserting it
+    /// against the live DOM on every single `Revision`, regardless of whether the memoized value
+    /// passed in has changed.
+    ///
+    /// [`attr`](Self::attr) sets an HTML attribute and only touches the DOM when the memoized
+    /// value changes, which is right for static configuration but wrong for interactive form
+    /// elements: their live `value`/`checked` properties are free to diverge from whatever
+    /// attribute was last set as the user types or clicks, and a change that only re-renders
+    /// identical props (because the component's state didn't change) would otherwise leave that
+    /// drift in place. Always re-asserting the declared value is what makes this a "controlled"
+    /// element in the React sense -- if an `oninput` handler rejects or transforms a keystroke,
+    /// the very next `Revision` forces the element back to what the component actually declared.
+    pub fn prop(&self, name: &'static str, value: impl Into<wasm_bindgen::JsValue>) -> &Self {
+        js_sys::Reflect::set(self.elem.as_ref(), &wasm_bindgen::JsValue::from_str(name), &value.into())
+            .unwrap();
+        self
     }
 
-    /// Declare the inner contents of the element, usually declaring children within the inner
-    /// scope. After any children have been run and their nodes attached, this clears any trailing
-    /// child nodes to ensure the element's children are correct per the latest declaration.
-    // FIXME this should be topo-aware
-    pub fn inner<Ret>(&self, children: impl FnOnce() -> Ret) -> Ret {
-        let elem = self.elem.clone();
-        let last_desired_child;
-        let ret;
-        topo::call!(
-            {
-                ret = children();
+    /// Declare the live `value` of a controlled form input (`<input>`, `<textarea>`, `<select>`),
+    /// re-asserted on every `Revision`. See [`prop`](Self::prop).
+    pub fn value(&self, value: impl ToString) -> &Self {
+        self.prop("value", wasm_bindgen::JsValue::from_str(&value.to_string()))
+    }
 
-                // before this melement is dropped when the environment goes out of scope,
-                // we need to get the last recorded child from this revision
-                last_desired_child = topo::Env::expect::<MemoElement>().curr.replace(None);
-            },
-            env! {
-                MemoElement => MemoElement::new(self.elem.clone()),
-            }
-        );
+    /// Declare the live `checked` state of a controlled checkbox or radio `<input>`, reasserted on
+    /// every `Revision`. See [`prop`](Self::prop).
+    pub fn checked(&self, checked: bool) -> &Self {
+        self.prop("checked", wasm_bindgen::JsValue::from_bool(checked))
+    }
+}
 
-        // if there weren't any children declared this revision, we need to make sure we clean up
-        // any from the last revision
-        let mut next_to_remove = if let Some(c) = last_desired_child {
-            c.next_sibling()
-        } else {
-            elem.first_child()
-        };
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::backend::StringBackend};
 
-        while let Some(to_remove) = next_to_remove {
-            next_to_remove = to_remove.next_sibling();
-            elem.remove_child(&to_remove).unwrap();
-        }
+    #[test]
+    fn render_to_string_renders_attributes_and_text() {
+        let html = render_to_string(|| {
+            element::<StringBackend, _>("div", |e| {
+                e.attr("id", "greeting");
+                e.inner(|| {
+                    text::<StringBackend>("hello");
+                });
+            });
+        });
+        assert_eq!(html, r#"<div id="greeting">hello</div>"#);
+    }
 
-        ret
+    #[test]
+    fn render_to_string_renders_fragment_siblings_without_a_wrapper() {
+        let html = render_to_string(|| {
+            fragment(|| {
+                element::<StringBackend, _>("a", |_| {});
+                element::<StringBackend, _>("b", |_| {});
+            });
+        });
+        assert_eq!(html, "<a></a><b></b>");
     }
 }
@@ -0,0 +1,383 @@
+//! Records DOM mutations as a serializable stream of [`Edit`]s instead of applying them directly,
+//! so the component tree can run on a server and ship only the changes to a thin client that
+//! replays them against its own DOM (a LiveView-style split). See [`MutationBackend`] to record
+//! edits and [`apply`] to replay them via [`WebSysBackend`](crate::backend::WebSysBackend).
+
+use {
+    crate::backend::{DomBackend, WebSysBackend},
+    std::{
+        cell::{Cell, RefCell},
+        collections::HashMap,
+        rc::Rc,
+    },
+};
+
+/// A stable id assigned to a node the first time it's created, used to address it in the [`Edit`]
+/// stream without needing a live node reference (or even a real DOM) to produce the stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(u32);
+
+/// A single DOM mutation, addressed by [`NodeId`] so the whole sequence can be serialized and
+/// replayed by a client that never executed the component tree which produced it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Edit {
+    /// Create a detached element of type `ty` and assign it `id`.
+    CreateElement {
+        /// id this element is addressed by in later edits
+        id: NodeId,
+        /// the element's tag name, e.g. `"div"`
+        ty: String,
+    },
+    /// Create a detached text node and assign it `id`.
+    CreateText {
+        /// id this node is addressed by in later edits
+        id: NodeId,
+        /// the text node's contents
+        value: String,
+    },
+    /// Set an attribute on the element addressed by `id`.
+    SetAttribute {
+        /// the element to mutate
+        id: NodeId,
+        /// attribute name
+        name: String,
+        /// attribute value
+        value: String,
+    },
+    /// Remove an attribute from the element addressed by `id`.
+    RemoveAttribute {
+        /// the element to mutate
+        id: NodeId,
+        /// attribute name
+        name: String,
+    },
+    /// Append `child` as the last child of `parent`.
+    AppendChild {
+        /// the parent to mutate
+        parent: NodeId,
+        /// the child to append
+        child: NodeId,
+    },
+    /// Replace `old_child` with `new_child` under `parent`.
+    ReplaceChild {
+        /// the parent to mutate
+        parent: NodeId,
+        /// the node taking `old_child`'s place
+        new_child: NodeId,
+        /// the node being displaced
+        old_child: NodeId,
+    },
+    /// Remove `child` from `parent`'s children.
+    RemoveChild {
+        /// the parent to mutate
+        parent: NodeId,
+        /// the child to remove
+        child: NodeId,
+    },
+    /// Move (or insert) `child` into `parent`'s children immediately before `reference`, or as
+    /// the last child if `reference` is `None`. Used by keyed reconciliation to reposition an
+    /// existing child without recreating it.
+    InsertBefore {
+        /// the parent to mutate
+        parent: NodeId,
+        /// the child to move or insert
+        child: NodeId,
+        /// the child `child` should precede, or `None` to move it to the end
+        reference: Option<NodeId>,
+    },
+    /// Install a listener for `event` on the element addressed by `id`. Only the event name is
+    /// serialized here; the handler closure isn't, because it cannot be: the client instead sends `(id, event)` back to the
+    /// server over its own channel, where the real closure was kept and is invoked in response.
+    NewEventListener {
+        /// the element the listener is attached to
+        id: NodeId,
+        /// the DOM event name, e.g. `"click"`
+        event: &'static str,
+    },
+}
+
+/// An append-only buffer of [`Edit`]s produced while declaring a `Revision`, ready to be
+/// [`drain`](Self::drain)ed and sent to a client, e.g. as a `serde`-serialized WebSocket message.
+#[derive(Clone, Debug, Default)]
+pub struct Mutations {
+    edits: Vec<Edit>,
+}
+
+impl Mutations {
+    fn push(&mut self, edit: Edit) {
+        self.edits.push(edit);
+    }
+
+    /// Remove and return all edits recorded so far, leaving the buffer empty.
+    pub fn drain(&mut self) -> Vec<Edit> {
+        std::mem::take(&mut self.edits)
+    }
+
+    /// Whether any edits have been recorded since the last [`drain`](Self::drain).
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+/// A [`DomBackend`] that never touches a real DOM: every mutation is instead appended to a shared
+/// [`Mutations`] buffer as an [`Edit`], addressed by a freshly allocated [`NodeId`].
+///
+/// A minimal shadow tree (parent id -> ordered child ids) is kept alongside the edit log purely so
+/// [`MemoElement::inner`](crate::MemoElement::inner) can still diff against "the current
+/// children" the way it does against a live backend; the shadow tree itself is never sent anywhere.
+#[derive(Clone, Default)]
+pub struct MutationBackend {
+    next_id: Rc<Cell<u32>>,
+    children: Rc<RefCell<HashMap<NodeId, Vec<NodeId>>>>,
+    mutations: Rc<RefCell<Mutations>>,
+    listeners: Rc<RefCell<HashMap<(NodeId, String), Box<dyn FnMut()>>>>,
+}
+
+impl MutationBackend {
+    /// Create a fresh backend with an empty mutation log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn alloc_id(&self) -> NodeId {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        NodeId(id)
+    }
+
+    /// Access the buffer of edits recorded so far, e.g. to [`Mutations::drain`] and ship them to a
+    /// client.
+    pub fn mutations(&self) -> Rc<RefCell<Mutations>> {
+        self.mutations.clone()
+    }
+
+    /// Invoke the callback registered via [`MemoElement::on`](crate::MemoElement::on) for `id` and
+    /// `event`, if any is still registered -- the entry point for the `(id, event name)` pair a
+    /// client forwards back over its own channel in response to an [`Edit::NewEventListener`].
+    /// A no-op if nothing is registered for the pair, e.g. because the element was since removed.
+    pub fn dispatch(&self, id: NodeId, event: &str) {
+        if let Some(callback) = self.listeners.borrow_mut().get_mut(&(id, event.to_string())) {
+            callback();
+        }
+    }
+}
+
+impl DomBackend for MutationBackend {
+    type Node = NodeId;
+    type Element = NodeId;
+
+    fn create_element(&self, ty: &str) -> Self::Element {
+        let id = self.alloc_id();
+        self.children.borrow_mut().insert(id, Vec::new());
+        self.mutations.borrow_mut().push(Edit::CreateElement { id, ty: ty.to_string() });
+        id
+    }
+
+    fn create_text(&self, text: &str) -> Self::Node {
+        let id = self.alloc_id();
+        self.mutations.borrow_mut().push(Edit::CreateText { id, value: text.to_string() });
+        id
+    }
+
+    fn set_attribute(&self, elem: &Self::Element, name: &str, value: &str) {
+        self.mutations.borrow_mut().push(Edit::SetAttribute {
+            id: *elem,
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    fn remove_attribute(&self, elem: &Self::Element, name: &str) {
+        self.mutations
+            .borrow_mut()
+            .push(Edit::RemoveAttribute { id: *elem, name: name.to_string() });
+    }
+
+    fn append_child(&self, parent: &Self::Element, child: &Self::Node) {
+        if let Some(siblings) = self.children.borrow_mut().get_mut(parent) {
+            siblings.push(*child);
+        }
+        self.mutations
+            .borrow_mut()
+            .push(Edit::AppendChild { parent: *parent, child: *child });
+    }
+
+    fn replace_child(&self, parent: &Self::Element, new_child: &Self::Node, existing: &Self::Node) {
+        if let Some(siblings) = self.children.borrow_mut().get_mut(parent) {
+            if let Some(slot) = siblings.iter_mut().find(|c| *c == existing) {
+                *slot = *new_child;
+            }
+        }
+        self.mutations.borrow_mut().push(Edit::ReplaceChild {
+            parent: *parent,
+            new_child: *new_child,
+            old_child: *existing,
+        });
+    }
+
+    fn remove_child(&self, parent: &Self::Element, child: &Self::Node) {
+        if let Some(siblings) = self.children.borrow_mut().get_mut(parent) {
+            siblings.retain(|c| c != child);
+        }
+        self.mutations
+            .borrow_mut()
+            .push(Edit::RemoveChild { parent: *parent, child: *child });
+    }
+
+    fn insert_before(&self, parent: &Self::Element, child: &Self::Node, reference: Option<&Self::Node>) {
+        if let Some(siblings) = self.children.borrow_mut().get_mut(parent) {
+            siblings.retain(|c| c != child);
+            let index = match reference {
+                Some(reference) => siblings.iter().position(|c| c == reference).unwrap_or(siblings.len()),
+                None => siblings.len(),
+            };
+            siblings.insert(index, *child);
+        }
+        self.mutations.borrow_mut().push(Edit::InsertBefore {
+            parent: *parent,
+            child: *child,
+            reference: reference.copied(),
+        });
+    }
+
+    fn first_child(&self, parent: &Self::Element) -> Option<Self::Node> {
+        self.children.borrow().get(parent).and_then(|c| c.first().copied())
+    }
+
+    fn next_sibling(&self, node: &Self::Node) -> Option<Self::Node> {
+        let children = self.children.borrow();
+        children.values().find_map(|siblings| {
+            let idx = siblings.iter().position(|c| c == node)?;
+            siblings.get(idx + 1).copied()
+        })
+    }
+}
+
+impl crate::MemoElement<MutationBackend> {
+    /// Declare an event handler on the element, recording an [`Edit::NewEventListener`] on every
+    /// `Revision` this callsite is declared so the client knows to forward matching events back to the server by `(id, event
+    /// name)`, and registering `callback` in the backend's dispatch table under that same pair.
+    /// Unlike [`MemoElement::on`](crate::MemoElement::on) there's no live `web_sys::Event` to hand
+    /// the callback here -- in the LiveView model the handler typically re-reads whatever state it
+    /// needs rather than inspecting the event object, so it's invoked with no arguments, from
+    /// [`MutationBackend::dispatch`], once the server receives the matching `(id, event name)`
+    /// round-trip. The registration is removed when this callsite is no longer declared.
+    ///
+    /// As with [`MemoElement::on`](crate::MemoElement::on), this is keyed on
+    /// `moxie::embed::Revision::current()` rather than `callback` itself, so the registered closure
+    /// is replaced on every single `Revision` -- `callback` typically closes over current state, and
+    /// a component re-rendered with new state but an unchanged callsite should still dispatch to the
+    /// latest closure rather than the one captured the first time this was declared.
+    pub fn on(&self, event: &'static str, callback: impl FnMut() + 'static) -> &Self {
+        let id = self.raw_node_id();
+        let backend = self.backend.clone();
+        topo::call!(slot: event, {
+            memo_with!(
+                moxie::embed::Revision::current(),
+                |_| {
+                    backend.mutations.borrow_mut().push(Edit::NewEventListener { id, event });
+                    backend.listeners.borrow_mut().insert((id, event.to_string()), Box::new(callback));
+                    let backend = backend.clone();
+                    scopeguard::guard((), move |_| {
+                        backend.listeners.borrow_mut().remove(&(id, event.to_string()));
+                    })
+                },
+                |_| {}
+            );
+        });
+        self
+    }
+}
+
+/// Replays a stream of [`Edit`]s against a live DOM via [`WebSysBackend`] -- the client-side half
+/// of the split this module enables: the server runs the component tree and records edits, and
+/// this interprets them to keep a thin client's real DOM in sync without running any component
+/// code at all. `known` maps server-assigned [`NodeId`]s to the real nodes they resolve to on this
+/// client, and grows as `CreateElement`/`CreateText` edits are replayed.
+pub fn apply(dom: &WebSysBackend, known: &mut HashMap<NodeId, crate::sys::Node>, edits: impl IntoIterator<Item = Edit>) {
+    use {crate::sys, wasm_bindgen::JsCast};
+
+    let element = |known: &HashMap<NodeId, sys::Node>, id: NodeId| -> sys::Element {
+        known.get(&id).expect("unknown node id").clone().unchecked_into()
+    };
+
+    for edit in edits {
+        match edit {
+            Edit::CreateElement { id, ty } => {
+                known.insert(id, dom.create_element(&ty).into());
+            }
+            Edit::CreateText { id, value } => {
+                known.insert(id, dom.create_text(&value));
+            }
+            Edit::SetAttribute { id, name, value } => {
+                dom.set_attribute(&element(known, id), &name, &value);
+            }
+            Edit::RemoveAttribute { id, name } => {
+                dom.remove_attribute(&element(known, id), &name);
+            }
+            Edit::AppendChild { parent, child } => {
+                dom.append_child(&element(known, parent), &known[&child]);
+            }
+            Edit::ReplaceChild { parent, new_child, old_child } => {
+                dom.replace_child(&element(known, parent), &known[&new_child], &known[&old_child]);
+            }
+            Edit::RemoveChild { parent, child } => {
+                dom.remove_child(&element(known, parent), &known[&child]);
+            }
+            Edit::InsertBefore { parent, child, reference } => {
+                dom.insert_before(
+                    &element(known, parent),
+                    &known[&child],
+                    reference.map(|r| &known[&r]),
+                );
+            }
+            Edit::NewEventListener { .. } => {
+                // the client only needs to know to forward this (id, event) pair back to the
+                // server over its own channel, where it's handed to `MutationBackend::dispatch`;
+                // installing the forwarding listener itself is the embedding application's
+                // responsibility, not this interpreter's.
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{element, MemoElement},
+        std::{cell::Cell, rc::Rc},
+    };
+
+    #[test]
+    fn dispatch_invokes_the_registered_callback() {
+        let backend = MutationBackend::new();
+        let root = backend.create_element("div");
+        let clicked = Rc::new(Cell::new(false));
+        let mut id = None;
+
+        topo::call!(
+            {
+                id = Some(element::<MutationBackend, _>("button", |e| {
+                    let clicked = clicked.clone();
+                    e.on("click", move || clicked.set(true));
+                    e.raw_node_id()
+                }));
+            },
+            env! {
+                MemoElement<MutationBackend> => MemoElement::new(backend.clone(), root),
+            }
+        );
+
+        backend.dispatch(id.unwrap(), "click");
+        assert!(clicked.get());
+    }
+
+    #[test]
+    fn dispatch_is_a_no_op_for_an_unregistered_pair() {
+        let backend = MutationBackend::new();
+        backend.dispatch(NodeId(0), "click");
+    }
+}
@@ -67,6 +67,8 @@ pub mod embed;
 pub mod load;
 pub mod memo;
 pub mod state;
+#[cfg(feature = "loading")]
+pub mod suspense;
 
 /// Accepts an XML-like expression and expands it to builder method calls.
 // TODO(#87) document when an api is decided upon
@@ -79,5 +81,7 @@ pub mod prelude {
         memo::{memo, memo_with, once, once_with},
         state::{memo_state, state, Key},
     };
+    #[cfg(feature = "loading")]
+    pub use crate::suspense::suspense;
     pub use topo;
 }
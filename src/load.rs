@@ -0,0 +1,58 @@
+//! Declarative async loading, integrated with [`crate::suspense`] boundaries: a [`load`] call
+//! reports itself pending to the nearest enclosing boundary as soon as its future is spawned, and
+//! resolved once that future completes, so the boundary knows when it's safe to stop rendering its
+//! fallback in favor of the children that depend on the loaded value.
+
+use {
+    crate::{
+        state::{state, Key},
+        suspense::SuspenseBoundary,
+    },
+    std::future::Future,
+};
+
+/// The status of a value being fetched by [`load`].
+pub enum Loaded<T> {
+    /// `init`'s future hasn't resolved yet.
+    Pending,
+    /// `init`'s future resolved to this value.
+    Done(T),
+}
+
+/// Spawn `init`'s future, memoized at this callsite so it's only ever started once (until the
+/// callsite itself stops being declared). Returns [`Loaded::Pending`] until the future resolves
+/// and [`Loaded::Done`] on every `Revision` after.
+///
+/// If a [`suspense`](crate::suspense::suspense) boundary encloses this callsite, it's fetched from
+/// the environment and told the load is pending as soon as the future is spawned, and told it's
+/// resolved once the value comes back -- see [`SuspenseBoundary::pending`] and
+/// [`SuspenseBoundary::resolved`].
+#[topo::aware]
+pub fn load<T, Fut>(init: impl FnOnce() -> Fut) -> Loaded<T>
+where
+    T: 'static,
+    Fut: Future<Output = T> + 'static,
+{
+    let (result, key): (Option<T>, Key<Option<T>>) = state(|| None);
+
+    once!(|| {
+        let boundary = topo::Env::get::<SuspenseBoundary>().map(|b| (*b).clone());
+        if let Some(boundary) = &boundary {
+            boundary.pending();
+        }
+
+        let key = key.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let value = init().await;
+            key.update(move |_| Some(Some(value)));
+            if let Some(boundary) = boundary {
+                boundary.resolved();
+            }
+        });
+    });
+
+    match result {
+        Some(value) => Loaded::Done(value),
+        None => Loaded::Pending,
+    }
+}
@@ -0,0 +1,60 @@
+//! Suspense boundaries that render placeholder fallback UI in place of a subtree whose
+//! [`load`](crate::load) calls haven't resolved yet, switching over once they have.
+
+use crate::state::{state, Key};
+
+/// Tracks the number of loads still outstanding within the nearest enclosing [`suspense`]
+/// boundary. [`load`](crate::load)-style functions fetch the current boundary from the
+/// environment (`topo::Env::get::<SuspenseBoundary>()`) and call [`Self::pending`] /
+/// [`Self::resolved`] around the future they're awaiting, so that the boundary knows whether it's
+/// still waiting on anything.
+///
+/// The count backing this is a memoized [`state`] variable rather than a plain counter, so that
+/// [`Self::resolved`] -- typically called from a spawned future once it completes, outside of any
+/// `Revision` -- goes through the same state-change path as any other update and wakes
+/// [`run_on_state_changes`](crate::embed::Runtime::run_on_state_changes), scheduling the `Revision`
+/// that switches `suspense` back over to rendering `children`.
+#[derive(Clone)]
+pub struct SuspenseBoundary {
+    pending: Key<usize>,
+}
+
+impl SuspenseBoundary {
+    fn new(pending: Key<usize>) -> Self {
+        Self { pending }
+    }
+
+    /// Record that one more load is pending within this boundary. Call [`Self::resolved`] once it
+    /// completes; typically via a `scopeguard` so it's called even if the load is torn down
+    /// before resolving.
+    pub fn pending(&self) {
+        self.pending.update(|p| Some(p + 1));
+    }
+
+    /// Record that a previously-[`pending`](Self::pending) load within this boundary has
+    /// resolved.
+    pub fn resolved(&self) {
+        self.pending.update(|p| Some(p.saturating_sub(1)));
+    }
+}
+
+/// Show `fallback` in place of `children` for as long as a [`load`](crate::load) call somewhere
+/// within `children` has a future that hasn't resolved yet, switching over to `children` on the
+/// `Revision` after the last of them completes.
+///
+/// The boundary wraps a [`state`] variable memoized at this callsite, so it persists across
+/// `Revision`s the way any other state does. While it's reporting any pending loads, `children`
+/// isn't invoked at all -- only `fallback` is -- so a suspended subtree's loads are left exactly as
+/// pending as they were, to be driven to completion independently (e.g. by a future already
+/// spawned the first time `children` ran) rather than re-started every `Revision`.
+#[topo::aware]
+pub fn suspense(mut fallback: impl FnMut(), mut children: impl FnMut()) {
+    let (pending_count, pending_key) = state(|| 0usize);
+    let boundary = SuspenseBoundary::new(pending_key);
+
+    if pending_count > 0 {
+        fallback();
+    } else {
+        topo::call!({ children() }, env! { SuspenseBoundary => boundary });
+    }
+}